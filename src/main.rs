@@ -1,415 +1,1418 @@
 use anyhow::{Context, Result, anyhow};
-use chrono::Local;
-use reqwest::blocking::{Client, Response};
-use reqwest::header::{HeaderMap, HeaderValue, USER_AGENT};
+use chrono::NaiveDate;
+use clap::parser::ValueSource;
+use clap::{ArgAction, ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use hn_daily::{
+    dedup_stories, digest_files, domain_matches, fetch_article_content, fetch_article_content_via,
+    fetch_comments, fetch_comments_via, fetch_front_page, fetch_front_page_via, fetch_url_content,
+    fetch_url_content_via, group_similar, load_json_digest, load_raw_snapshots, normalize_language,
+    normalize_url, post_webhook, render_archive, render_article, render_bookmarks, render_epub,
+    render_feed, render_html, render_json, render_json_feed, render_markdown, render_mbox,
+    render_opml, render_terminal, render_text, repair_url, select_recent_days, slowest_sites,
+    sort_stories, spread_domains, story_date, story_domain, user_agent, webhook_payload, write_pdf,
+    Cache, ContentOptions, ExtractRule, Extractor, Feed, Item, Layout, MockHttp, RenderOptions,
+    ScrapedContent, Snapshot, SortOrder, Theme, WebhookFormat, Zone, DEFAULT_CACHE_TTL_HOURS,
+    DEFAULT_CONCURRENCY, DEFAULT_CONCURRENCY_PER_HOST, DEFAULT_LIMIT, DEFAULT_MAX_PAGE_MB,
+    DEFAULT_MAX_RETRIES, DEFAULT_THIN_WORDS, DEFAULT_TIMEOUT_SECS, MAX_LIMIT,
+};
+use log::{debug, info, warn, LevelFilter};
 use serde::Deserialize;
-use std::{fs, path::PathBuf, time::Duration};
-use url::Url;
-use readability::extractor;
-use scraper::Html;
-
-const TOP_URL: &str = "https://hacker-news.firebaseio.com/v0/topstories.json";
-const ITEM_URL: &str = "https://hacker-news.firebaseio.com/v0/item/";
-const BROWSER_UA: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Safari/537.36";
-const CONTENT_FETCH_TIMEOUT: u64 = 10; // 10 seconds timeout
-// No longer needed since we're showing full content instead of previews
-
-#[derive(Deserialize)]
-struct Item {
-    #[allow(dead_code)]
-    id: u64,
-    by: Option<String>,
-    score: Option<u32>,
-    #[allow(dead_code)]
-    time: Option<u64>,
-    title: Option<String>,
-    url: Option<String>,
-    descendants: Option<u32>,
-}
-
-#[derive(Debug)]
-struct ScrapedContent {
-    #[allow(dead_code)]
-    title: String,
-    // Original plaintext content
-    #[allow(dead_code)]
-    content: String,
-    // HTML content for rendering
-    content_html: String,
-    is_paywall: bool,
-    domain: String,
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+use std::path::{Path, PathBuf};
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+mod serve;
+mod stats;
+
+/// Output file produced by a run
+#[derive(Clone, Copy, PartialEq, Eq, Hash, ValueEnum)]
+enum Format {
+    Html,
+    #[value(alias = "txt")]
+    Text,
+    #[value(alias = "markdown")]
+    Md,
+    Json,
+    Rss,
+    Epub,
+    Pdf,
+    Mbox,
+    Opml,
+    JsonFeed,
+    /// Article links as a bookmarks file for Pocket or Instapaper
+    #[value(aliases = ["pocket", "instapaper"])]
+    Bookmarks,
+    /// Print an overview to the console instead of writing files
+    Terminal,
+}
+
+/// What to do with articles that look paywalled
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum PaywallMode {
+    /// Show what could be scraped, with a warning
+    Show,
+    /// List the story without its article
+    TitleOnly,
+    /// Drop the story
+    Skip,
+}
+
+/// Build a reader-friendly daily digest of Hacker News stories
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    /// Number of stories to fetch (1-500)
+    #[arg(short, long, default_value_t = DEFAULT_LIMIT, value_parser = parse_positive)]
+    limit: usize,
+
+    /// Which HN story list to build the digest from
+    #[arg(short, long, value_enum, default_value_t = Feed::Top)]
+    feed: Feed,
+
+    /// Maximum number of articles fetched in parallel
+    #[arg(short, long, default_value_t = DEFAULT_CONCURRENCY, value_parser = parse_positive)]
+    concurrency: usize,
+
+    /// Maximum number of those fetches going to the same site at once
+    #[arg(long, value_name = "N", default_value_t = DEFAULT_CONCURRENCY_PER_HOST, value_parser = parse_positive)]
+    concurrency_per_host: usize,
+
+    /// Retries for timeouts, dropped connections, 5xx and 429 responses
+    #[arg(long, default_value_t = DEFAULT_MAX_RETRIES)]
+    retries: u32,
+
+    /// Seconds to wait for each article download
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_TIMEOUT_SECS, value_parser = parse_positive_secs)]
+    timeout: u64,
+
+    /// Seconds to wait for each HN API request
+    #[arg(long, value_name = "SECONDS", default_value_t = DEFAULT_TIMEOUT_SECS, value_parser = parse_positive_secs)]
+    api_timeout: u64,
+
+    /// Skip articles whose page is bigger than this many megabytes
+    #[arg(long, value_name = "MB", default_value_t = DEFAULT_MAX_PAGE_MB)]
+    max_page_mb: u64,
+
+    /// POST the top stories to this Slack, Discord or other webhook URL
+    #[arg(long, value_name = "URL")]
+    webhook: Option<String>,
+
+    /// Message shape for --webhook
+    #[arg(long, value_enum, default_value_t = WebhookFormat::Generic, requires = "webhook")]
+    webhook_format: WebhookFormat,
+
+    /// Number of stories to announce with --webhook
+    #[arg(long, value_name = "N", default_value_t = 5, value_parser = parse_positive, requires = "webhook")]
+    webhook_top: usize,
+
+    /// Flag articles with fewer extracted words than this as partial (0 to turn off)
+    #[arg(long, value_name = "WORDS", default_value_t = DEFAULT_THIN_WORDS)]
+    thin_words: usize,
+
+    /// Idle connections kept open per site between article downloads [default: --concurrency]
+    #[arg(long, value_name = "N")]
+    max_idle_per_host: Option<usize>,
+
+    /// User-Agent for article downloads: browser, bot, default, or any custom string
+    #[arg(long, value_name = "UA", default_value = "browser")]
+    user_agent: String,
+
+    /// Send cookies from this Netscape-format file (a browser export) to their own sites
+    #[arg(long, value_name = "FILE")]
+    cookies_file: Option<PathBuf>,
+
+    /// Send all requests through this proxy (http:// or https://, optionally with user:pass@)
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Minimum delay between HN API requests, in milliseconds
+    #[arg(long, default_value_t = 0)]
+    api_delay: u64,
+
+    /// Build a catch-up digest of stories posted over the last N days, grouped by date
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    since_days: Option<usize>,
+
+    /// Group stories whose articles are near-identical under the first of them
+    #[arg(long)]
+    group_similar: bool,
+
+    /// How alike two articles must be for --group-similar, from 0 (anything) to 1 (identical)
+    #[arg(long, value_name = "FRACTION", default_value_t = 0.5, value_parser = parse_fraction)]
+    similarity: f64,
+
+    /// Only fetch the articles of the first N stories; the rest are listed by title
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    top_n_content: Option<usize>,
+
+    /// Include up to N top-level comments under each article
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    with_comments: Option<usize>,
+
+    /// Only keep stories with an HN id above N, e.g. the newest id printed by the previous run
+    #[arg(long, value_name = "N")]
+    after_id: Option<u64>,
+
+    /// Only keep stories with an HN id below N
+    #[arg(long, value_name = "N")]
+    before_id: Option<u64>,
+
+    /// Leave out stories shown by earlier --skip-seen runs, and remember this run's
+    #[arg(long)]
+    skip_seen: bool,
+
+    /// Drop stories scoring below this many points
+    #[arg(long, value_name = "POINTS")]
+    min_score: Option<u32>,
+
+    /// Drop stories with fewer than this many comments
+    #[arg(long, value_name = "N")]
+    min_comments: Option<u32>,
+
+    /// Drop stories linking to these domains (comma-separated, subdomains included)
+    #[arg(long, value_name = "DOMAINS", value_delimiter = ',')]
+    exclude_domains: Vec<String>,
+
+    /// Only keep stories linking to these domains (comma-separated, subdomains included)
+    #[arg(long, value_name = "DOMAINS", value_delimiter = ',')]
+    include_domains: Vec<String>,
+
+    /// Drop articles detected to be in another language (ISO 639 code, e.g. en)
+    #[arg(long, value_name = "CODE", value_parser = parse_language)]
+    lang: Option<String>,
+
+    /// What to do with articles that look paywalled
+    #[arg(long, value_enum, default_value_t = PaywallMode::Show)]
+    paywall: PaywallMode,
+
+    /// Order to list stories in
+    #[arg(long, value_enum, default_value_t = SortOrder::Rank)]
+    sort: SortOrder,
+
+    /// Where top comments go in the HTML digest (with --with-comments)
+    #[arg(long, value_enum, default_value_t = Layout::ArticleFirst)]
+    layout: Layout,
+
+    /// Reorder stories so consecutive ones come from different sites where possible
+    #[arg(long)]
+    spread_domains: bool,
+
+    /// Show how long each article took to fetch in the HTML digest
+    #[arg(long)]
+    show_timings: bool,
+
+    /// Show each story's points and comment count in the HTML digest's sidebar
+    #[arg(long)]
+    sidebar_stats: bool,
+
+    /// Cut long articles in the HTML digest off after about N words, with a link to the rest
+    #[arg(long, value_name = "N", value_parser = parse_positive)]
+    max_content_words: Option<usize>,
+
+    /// Show each article's preview picture (og:image) above it in the HTML digest
+    #[arg(long)]
+    header_images: bool,
+
+    /// Show each source site's icon in the HTML digest (loaded from Google when viewed)
+    #[arg(long)]
+    favicons: bool,
+
+    /// Column to wrap the plain text digest at
+    #[arg(long, value_name = "COLUMNS", default_value_t = 80, value_parser = parse_positive)]
+    text_width: usize,
+
+    /// Color scheme of the HTML digest
+    #[arg(short, long, value_enum, default_value_t = Theme::Light)]
+    theme: Theme,
+
+    /// Directory digests are written to [default: ~/hn_daily]
+    #[arg(short, long, env = "HN_DAILY_DIR", global = true)]
+    out_dir: Option<PathBuf>,
+
+    /// Name for the digest files, before the extension: {date}, {feed} and {count} are filled
+    /// in, and `/` puts them in a subdirectory
+    #[arg(long, value_name = "TEMPLATE", default_value = "{date}", value_parser = parse_filename_template, global = true)]
+    filename_template: String,
+
+    /// Query parameter to keep even if it looks like tracking (repeatable)
+    #[arg(long = "keep-param", value_name = "NAME")]
+    keep_params: Vec<String>,
+
+    /// Download an article once more when nothing could be extracted from it
+    #[arg(long)]
+    retry_empty: bool,
+
+    /// How article text is found on sites without an extraction rule (DOM Distiller isn't available)
+    #[arg(long, value_enum, default_value_t = Extractor::Readability)]
+    extractor: Extractor,
+
+    /// Retry paywalled or failed articles through the Wayback Machine
+    #[arg(long)]
+    wayback: bool,
+
+    /// Save each article's raw HTML under raw/DATE/ for later re-extraction
+    #[arg(long, conflicts_with = "from_raw")]
+    save_raw: bool,
+
+    /// Embed article images in the HTML digest so it displays without network access
+    #[arg(long)]
+    inline_images: bool,
+
+    /// Leave images larger than this many kilobytes out of --inline-images
+    #[arg(long, value_name = "KB", default_value_t = 500)]
+    max_image_kb: usize,
+
+    /// Serve the HN API and articles from fixtures in DIR (laid out like a --save-raw snapshot) instead of the network
+    #[arg(long, value_name = "DIR", conflicts_with = "from_raw")]
+    mock: Option<PathBuf>,
+
+    /// Date to stamp the digest with (YYYY-MM-DD) instead of today
+    #[arg(long, value_name = "DATE", value_parser = parse_date, conflicts_with = "from_raw")]
+    date: Option<NaiveDate>,
+
+    /// Time zone for the digest's date and times (IANA name, e.g. Europe/Berlin) [default: the system's]
+    #[arg(long, value_name = "ZONE", value_parser = parse_timezone)]
+    timezone: Option<Zone>,
+
+    /// Rebuild the digest for DATE (YYYY-MM-DD) from saved raw HTML, without network access
+    #[arg(long, value_name = "DATE", value_parser = parse_date)]
+    from_raw: Option<NaiveDate>,
+
+    /// Comma-separated outputs to write [default: all but mbox, opml and terminal; pdf only if a backend is available]
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FORMATS")]
+    formats: Option<Vec<Format>>,
+
+    /// Write the digest to standard output instead of a file (needs a single --formats)
+    #[arg(long, requires = "formats", conflicts_with = "open")]
+    stdout: bool,
+
+    /// List the stories a run would fetch, without downloading articles or writing any files
+    #[arg(long, conflicts_with = "from_raw")]
+    dry_run: bool,
+
+    /// Open the HTML digest in the default browser when done
+    #[arg(long)]
+    open: bool,
+
+    /// Hours a cached article stays fresh
+    #[arg(long, default_value_t = DEFAULT_CACHE_TTL_HOURS)]
+    cache_ttl: u64,
+
+    /// Ignore the article cache entirely (no reads, no writes)
+    #[arg(long, conflicts_with = "refresh")]
+    no_cache: bool,
+
+    /// Continue an interrupted run for today, reusing the articles it already fetched
+    #[arg(long, conflicts_with = "from_raw")]
+    resume: bool,
+
+    /// Re-fetch every article but still update the cache
+    #[arg(long)]
+    refresh: bool,
+
+    /// Download the story id list even if a run in the last 5 minutes saved one
+    #[arg(long)]
+    fresh_ids: bool,
+
+    /// Show progress (-v) or every fetched URL (-vv)
+    #[arg(short, long, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// Only report errors
+    #[arg(short, long)]
+    quiet: bool,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Browse the generated digests from a local web server
+    Serve {
+        /// Port to listen on
+        #[arg(long, default_value_t = 8080)]
+        port: u16,
+
+        /// Address to listen on; use 0.0.0.0 to reach the server from other machines
+        #[arg(long, default_value = "127.0.0.1")]
+        bind: String,
+    },
+    /// Clear the record of stories shown by --skip-seen
+    Forget,
+    /// Rank the sites that turn up most across the saved JSON digests
+    Stats {
+        /// Number of sites to list
+        #[arg(long, default_value_t = 20, value_parser = parse_positive)]
+        top: usize,
+
+        /// Also write every site's numbers to FILE as CSV
+        #[arg(long, value_name = "FILE")]
+        csv: Option<PathBuf>,
+    },
+    /// Rebuild outputs from a saved JSON digest, without going online
+    Render {
+        /// JSON digest written by an earlier run
+        #[arg(long, value_name = "FILE")]
+        from_json: PathBuf,
+
+        /// Comma-separated outputs to write
+        #[arg(long = "format", value_enum, value_delimiter = ',', value_name = "FORMATS", default_value = "html")]
+        formats: Vec<Format>,
+    },
+    /// Print the readable version of a single article
+    Read {
+        /// Address of the article
+        url: String,
+
+        /// Output plain text instead of HTML
+        #[arg(long)]
+        text: bool,
+
+        /// Write to FILE instead of standard output
+        #[arg(long, value_name = "FILE")]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Defaults read from `hn_daily/config.toml` in the user config directory
+/// (`~/.config` on Linux). Precedence is file < environment < command line.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+struct Config {
+    /// `limit = 30`
+    limit: Option<usize>,
+    /// `feed = "top"` (top, new, best, ask, show or job)
+    feed: Option<Feed>,
+    /// `out_dir = "/var/www/hn"`
+    out_dir: Option<PathBuf>,
+    /// `filename_template = "hn-{feed}-{date}"`
+    filename_template: Option<String>,
+    /// `theme = "auto"` (light, dark or auto)
+    theme: Option<Theme>,
+    /// `concurrency = 8`
+    concurrency: Option<usize>,
+    /// `user_agent = "bot"` (browser, bot, default, or a custom string)
+    user_agent: Option<String>,
+    /// `timezone = "America/New_York"`
+    timezone: Option<String>,
+    /// `cookies_file = "/home/me/.config/hn_daily/cookies.txt"`
+    cookies_file: Option<PathBuf>,
+    /// `[[extract]]` tables of `domain` and `selector`, tried before the built-in rules
+    extract: Vec<ExtractRule>,
+}
+
+impl Config {
+    fn load() -> Result<Config> {
+        let Some(path) = dirs::config_dir().map(|dir| dir.join("hn_daily").join("config.toml")) else {
+            return Ok(Config::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => toml::from_str(&text)
+                .with_context(|| format!("invalid config file {}", path.display())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            Err(e) => Err(e).with_context(|| format!("cannot read config file {}", path.display())),
+        }
+    }
+
+    // The user's extraction rules, validated, followed by the built-in ones
+    fn extract_rules(&mut self) -> Result<Vec<ExtractRule>> {
+        let mut rules = std::mem::take(&mut self.extract);
+        for rule in &rules {
+            rule.validate().context("config")?;
+        }
+        rules.extend(ExtractRule::builtin());
+        Ok(rules)
+    }
+
+    // Fills in every option that was left at its built-in default
+    fn apply(self, args: &mut Args, matches: &ArgMatches) -> Result<()> {
+        let unset = |id: &str| {
+            matches!(matches.value_source(id), None | Some(ValueSource::DefaultValue))
+        };
+        
+        if let Some(limit) = self.limit.filter(|_| unset("limit")) {
+            if limit == 0 {
+                return Err(anyhow!("config: limit must be at least 1"));
+            }
+            args.limit = limit;
+        }
+        if let Some(concurrency) = self.concurrency.filter(|_| unset("concurrency")) {
+            if concurrency == 0 {
+                return Err(anyhow!("config: concurrency must be at least 1"));
+            }
+            args.concurrency = concurrency;
+        }
+        if let Some(feed) = self.feed.filter(|_| unset("feed")) {
+            args.feed = feed;
+        }
+        if let Some(theme) = self.theme.filter(|_| unset("theme")) {
+            args.theme = theme;
+        }
+        if let Some(out_dir) = self.out_dir.filter(|_| unset("out_dir")) {
+            args.out_dir = Some(out_dir);
+        }
+        if let Some(template) = self.filename_template.filter(|_| unset("filename_template")) {
+            args.filename_template = parse_filename_template(&template).map_err(|e| anyhow!("config: {}", e))?;
+        }
+        if let Some(timezone) = self.timezone.filter(|_| unset("timezone")) {
+            args.timezone = Some(parse_timezone(&timezone).map_err(|e| anyhow!("config: {}", e))?);
+        }
+        if let Some(cookies_file) = self.cookies_file.filter(|_| unset("cookies_file")) {
+            args.cookies_file = Some(cookies_file);
+        }
+        if let Some(user_agent) = self.user_agent.filter(|_| unset("user_agent")) {
+            args.user_agent = user_agent;
+        }
+        Ok(())
+    }
+}
+
+impl Format {
+    // Where the format is saved in the output directory, given the name from
+    // --filename-template; the feeds keep one fixed name
+    fn file_name(self, stem: &str) -> String {
+        match self {
+            Format::Html => format!("{}.html", stem),
+            Format::Text => format!("{}.txt", stem),
+            Format::Md => format!("{}.md", stem),
+            Format::Json => format!("{}.json", stem),
+            Format::Rss => "feed.xml".to_string(),
+            Format::Epub => format!("{}.epub", stem),
+            Format::Pdf => format!("{}.pdf", stem),
+            Format::Mbox => format!("{}.mbox", stem),
+            Format::Opml => format!("{}.opml", stem),
+            Format::JsonFeed => "feed.json".to_string(),
+            Format::Bookmarks => format!("{}-bookmarks.html", stem),
+            Format::Terminal => unreachable!("terminal output is never saved"),
+        }
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Format::Html => "HTML digest",
+            Format::Text => "Plain text digest",
+            Format::Md => "Markdown digest",
+            Format::Json => "JSON digest",
+            Format::Rss => "RSS feed",
+            Format::Epub => "EPUB digest",
+            Format::Pdf => "PDF digest",
+            Format::Mbox => "Mailbox of the stories",
+            Format::Opml => "Feeds of the day's sites",
+            Format::JsonFeed => "JSON Feed",
+            Format::Bookmarks => "Bookmarks of the day's articles",
+            Format::Terminal => "Console overview",
+        }
+    }
+}
+
+impl Args {
+    fn formats(&self) -> HashSet<Format> {
+        match &self.formats {
+            Some(formats) => formats.iter().copied().collect(),
+            // The mailbox, OPML, JSON Feed and bookmarks are extras, and the console view
+            // replaces the files, so these are only used on request
+            None => Format::value_variants()
+                .iter()
+                .copied()
+                .filter(|f| !matches!(f, Format::Mbox | Format::Opml | Format::JsonFeed | Format::Bookmarks | Format::Terminal))
+                .collect(),
+        }
+    }
 }
 
 fn main() -> Result<()> {
-    let stories = fetch_front_page(30)?;
-    let out_dir = dirs::home_dir().unwrap_or(PathBuf::from(".")).join("hn_daily");
-    fs::create_dir_all(&out_dir)?;
-    let date = Local::now().format("%Y-%m-%d").to_string();
-    
-    println!("Fetching article content (this may take a minute)...");
-    let stories_with_content = fetch_article_content(&stories)?;
-    
-    // Generate regular HTML with content
-    let html = render_html(&stories, &stories_with_content)?;
-    let html_path = out_dir.join(format!("{}.html", &date));
-    fs::write(&html_path, &html)?;
-
-    // Generate text version
-    let text = html2text::from_read(html.as_bytes(), 80);
-    let text_path = out_dir.join(format!("{}.txt", &date));
-    fs::write(&text_path, text)?;
-    
-    // optional: create PDF if wkhtmltopdf is present
-    if which::which("wkhtmltopdf").is_ok() {
-        let pdf_path = out_dir.join(format!("{}.pdf", &date));
-        std::process::Command::new("wkhtmltopdf")
-            .arg("--quiet")
-            .arg(&html_path)
-            .arg(&pdf_path)
-            .status()
-            .ok();
+    let matches = Args::command().get_matches();
+    let mut args = Args::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    init_logging(&args);
+    let mut config = Config::load()?;
+    let extract_rules = config.extract_rules()?;
+    config.apply(&mut args, &matches)?;
+    match &args.command {
+        Some(Command::Serve { port, bind }) => {
+            let out_dir = resolve_out_dir(args.out_dir.as_deref())?;
+            return serve::run(&out_dir, &args.filename_template, bind, *port, args.theme);
+        }
+        Some(Command::Read { url, text, output }) => {
+            return read_article(&args, url, *text, output.as_deref(), extract_rules);
+        }
+        Some(Command::Forget) => return forget_seen(&resolve_out_dir(args.out_dir.as_deref())?),
+        Some(Command::Stats { top, csv }) => {
+            return stats::run(&resolve_out_dir(args.out_dir.as_deref())?, *top, csv.as_deref());
+        }
+        Some(Command::Render { from_json, formats }) => {
+            let from_json = from_json.clone();
+            args.formats = Some(formats.clone());
+            return render_saved(&args, &from_json);
+        }
+        None => {}
+    }
+    let started = Instant::now();
+    let mut times = PhaseTimes::default();
+    let limit = clamp_limit(args.limit);
+    if args.dry_run {
+        let mock = args.mock.as_deref().map(MockHttp::load).transpose()?;
+        // Writes nothing, not even the id list cache or the seen stories
+        let seen = if args.skip_seen {
+            load_seen(&resolve_out_dir(args.out_dir.as_deref())?)?
+        } else {
+            HashSet::new()
+        };
+        let (stories, filtered) = fetch_stories(&args, limit, mock.as_ref(), None, &seen)?;
+        print_plan(&args, stories, filtered);
+        return Ok(());
+    }
+    let out_dir = prepare_out_dir(args.out_dir.as_deref())?;
+    // Everything date-stamped (file names, headings) follows this one day
+    let zone = args.timezone.unwrap_or_default();
+    let day = args.from_raw.or(args.date).unwrap_or_else(|| zone.today());
+    let date = day.format("%Y-%m-%d").to_string();
+    let (stories, stories_with_content, filtered) = if args.from_raw.is_some() {
+        info!("Rebuilding {} digest from saved raw HTML", date);
+        let raw_dir = out_dir.join("raw").join(&date);
+        let (stories, contents) = load_raw_snapshots(&raw_dir, &extract_rules, args.extractor, args.thin_words)?;
+        (stories, contents, 0)
+    } else {
+        fetch_digest(&args, limit, &out_dir, &date, extract_rules, &mut times)?
+    };
+
+    let (stories, stories_with_content) = match &args.lang {
+        Some(lang) => filter_language(stories, stories_with_content, lang),
+        None => (stories, stories_with_content),
+    };
+    let (stories, stories_with_content) = filter_paywalled(stories, stories_with_content, args.paywall);
+    let (stories, stories_with_content) = if args.group_similar {
+        let before = stories.len();
+        let grouped = group_similar(stories, stories_with_content, args.similarity);
+        info!("Grouped {} near-duplicate articles", before - grouped.0.len());
+        grouped
+    } else {
+        (stories, stories_with_content)
+    };
+    let by_day = args.since_days.is_some();
+    let (stories, stories_with_content) = sort_stories(stories, stories_with_content, args.sort, by_day, zone);
+    let (stories, stories_with_content) = if args.spread_domains {
+        spread_domains(stories, stories_with_content, by_day, zone)
+    } else {
+        (stories, stories_with_content)
+    };
+
+    let formats = args.formats();
+    let stem = file_stem(&args.filename_template, &date, args.feed.name(), stories.len());
+    // An earlier run today left its numbers behind; rebuilding from raw HTML has nothing newer to compare
+    let previous = match args.from_raw {
+        Some(_) => None,
+        None => Snapshot::load(&out_dir.join(Format::Json.file_name(&stem))),
+    };
+    if let Some(previous) = &previous {
+        info!("Showing changes since the digest written at {}", zone.clock(previous.taken));
+    }
+    let render_options = render_options(&args, day, zone, by_day, previous);
+
+    if formats.contains(&Format::Terminal) {
+        if formats.len() > 1 {
+            return Err(anyhow!("the terminal format writes no files; pass it to --formats on its own"));
+        }
+        let writing = Instant::now();
+        show_in_terminal(&args, &render_options, &stories, &stories_with_content)?;
+        times.writing = writing.elapsed();
+        times.report(started.elapsed());
+        remember_seen(&args, &out_dir, &stories)?;
+        announce(&args, &render_options, &stories, &stories_with_content);
+        eprint_newest_id(&args, &stories);
+        return Ok(());
+    }
+    if args.stdout {
+        let [format] = formats.iter().copied().collect::<Vec<_>>()[..] else {
+            return Err(anyhow!("--stdout writes a single format; pass just one to --formats"));
+        };
+        if format == Format::Pdf {
+            return Err(anyhow!("--stdout cannot write PDF; drop --stdout to save it to a file"));
+        }
+        let writing = Instant::now();
+        let mut stdout = io::stdout().lock();
+        write_digest(&mut stdout, format, &args, &render_options, &stories, &stories_with_content)?;
+        stdout.flush()?;
+        times.writing = writing.elapsed();
+        times.report(started.elapsed());
+        remember_seen(&args, &out_dir, &stories)?;
+        announce(&args, &render_options, &stories, &stories_with_content);
+        eprint_newest_id(&args, &stories);
+        return Ok(());
     }
 
-    println!("Files generated in {}", out_dir.display());
-    println!("- {}.html - HTML digest", date);
-    println!("- {}.txt - Plain text digest", date);
-    if which::which("wkhtmltopdf").is_ok() {
-        println!("- {}.pdf - PDF digest", date);
+    let writing = Instant::now();
+    let written = write_files(&args, &out_dir, &stem, &render_options, &stories, &stories_with_content)?;
+    times.writing = writing.elapsed();
+    info!("Files generated in {}", out_dir.display());
+    for (name, description) in &written {
+        info!("- {} - {}", name, description);
+    }
+    if filtered > 0 {
+        info!("Filtered out {} stories by domain", filtered);
+    }
+    let slowest = slowest_sites(&stories_with_content, 5);
+    if !slowest.is_empty() {
+        let sites: Vec<String> =
+            slowest.iter().map(|(domain, ms)| format!("{} {:.2}s", domain, *ms as f64 / 1000.0)).collect();
+        info!("Slowest sites: {}", sites.join(", "));
+    }
+    times.report(started.elapsed());
+    remember_seen(&args, &out_dir, &stories)?;
+    announce(&args, &render_options, &stories, &stories_with_content);
+    if let Some(newest) = newest_id(&args, &stories) {
+        println!("Newest story id: {}", newest);
+    }
+
+    if args.open {
+        if formats.contains(&Format::Html) {
+            open_in_browser(&out_dir.join(Format::Html.file_name(&stem)));
+        } else {
+            warn!("Not opening anything: --open needs the html format");
+        }
     }
 
     Ok(())
 }
 
-fn fetch_front_page(limit: usize) -> Result<Vec<Item>> {
-    let client = Client::builder()
-        .user_agent("hn_daily/0.1")
-        .timeout(Duration::from_secs(10))
-        .build()?;
-        
-    let ids: Vec<u64> = client
-        .get(TOP_URL)
-        .send()?
-        .json()
-        .context("topstories JSON")?;
-
-    let mut items = Vec::with_capacity(limit);
-    for id in ids.into_iter().take(limit) {
-        let item: Item = client
-            .get(format!("{id_url}{id}.json", id_url = ITEM_URL))
-            .send()?
-            .json()
-            .with_context(|| format!("item {id}"))?;
-        items.push(item);
-    }
-    Ok(items)
-}
-
-fn fetch_article_content(items: &[Item]) -> Result<Vec<Option<ScrapedContent>>> {
-    // Create browser-like headers to help with some paywalls
-    let mut headers = HeaderMap::new();
-    headers.insert(USER_AGENT, HeaderValue::from_static(BROWSER_UA));
+// Where a run spent its time, reported under -v to help tune concurrency and timeouts
+#[derive(Default)]
+struct PhaseTimes {
+    stories: Duration,
+    comments: Duration,
+    articles: Duration,
+    writing: Duration,
+}
+
+impl PhaseTimes {
+    fn report(&self, total: Duration) {
+        let phases = [
+            ("story list", self.stories),
+            ("comments", self.comments),
+            ("articles", self.articles),
+            ("rendering", self.writing),
+        ];
+        // Rebuilding from raw HTML or skipping comments leaves phases that never ran
+        let spent: Vec<String> = phases
+            .iter()
+            .filter(|(_, time)| !time.is_zero())
+            .map(|(phase, time)| format!("{} {:.2}s", phase, time.as_secs_f64()))
+            .collect();
+        info!("Took {:.2}s: {}", total.as_secs_f64(), spent.join(", "));
+    }
+}
+
+// The network path of `main`: story list, filtering, then article content. The count is
+// how many stories the domain filters dropped
+fn fetch_digest(
+    args: &Args,
+    limit: usize,
+    out_dir: &Path,
+    date: &str,
+    extract_rules: Vec<ExtractRule>,
+    times: &mut PhaseTimes,
+) -> Result<(Vec<Item>, Vec<Option<ScrapedContent>>, usize)> {
+    let mock = args.mock.as_deref().map(MockHttp::load).transpose()?;
+    let id_cache = out_dir.join("cache");
+    let use_id_cache = !args.fresh_ids && !args.no_cache;
+    let seen = if args.skip_seen { load_seen(out_dir)? } else { HashSet::new() };
+    let phase = Instant::now();
+    let (mut stories, filtered) =
+        fetch_stories(args, limit, mock.as_ref(), use_id_cache.then_some(id_cache.as_path()), &seen)?;
+    times.stories = phase.elapsed();
+    if let Some(per_story) = args.with_comments {
+        info!("Fetching up to {} comments per story", per_story);
+        let phase = Instant::now();
+        match &mock {
+            Some(http) => fetch_comments_via(http, &mut stories, per_story),
+            None => fetch_comments(
+                &mut stories,
+                per_story,
+                args.retries,
+                Duration::from_millis(args.api_delay),
+                Duration::from_secs(args.api_timeout),
+                args.proxy.as_deref(),
+            )?,
+        }
+        times.comments = phase.elapsed();
+    }
+    let cache = Cache {
+        dir: out_dir.join("cache"),
+        ttl: Duration::from_secs(args.cache_ttl * 3600),
+        // Raw snapshots need the page HTML, which is never cached. Fixture content
+        // must not leak into the real cache either.
+        read: !args.no_cache && !args.refresh && !args.save_raw && mock.is_none(),
+        write: !args.no_cache && mock.is_none(),
+    };
     
-    let client = Client::builder()
-        .default_headers(headers)
-        .timeout(Duration::from_secs(CONTENT_FETCH_TIMEOUT))
-        .build()?;
+    let raw_dir = if args.save_raw {
+        let raw_dir = out_dir.join("raw").join(date);
+        fs::create_dir_all(&raw_dir)?;
+        fs::write(raw_dir.join("items.json"), serde_json::to_vec(&stories)?)?;
+        Some(raw_dir)
+    } else {
+        None
+    };
     
-    // Process each URL
-    let mut results = Vec::with_capacity(items.len());
-    for item in items {
-        let url = match &item.url {
-            Some(url) if !url.is_empty() => url,
-            _ => {
-                // Skip items without URLs (e.g., "Ask HN" posts)
-                results.push(None);
-                continue;
-            }
+    info!("Fetching article content (this may take a minute)...");
+    let content_options = ContentOptions {
+        raw_dir,
+        progress_file: Some(out_dir.join(format!(".{}.partial", date))),
+        ..content_options(args, extract_rules)
+    };
+    // A first Ctrl-C stops new downloads so the digest is written with what's been fetched
+    let interrupt = Arc::new(AtomicBool::new(false));
+    let flag = Arc::clone(&interrupt);
+    let handled = ctrlc::set_handler(move || {
+        if flag.swap(true, Ordering::Relaxed) {
+            std::process::exit(130);
+        }
+        eprintln!("Interrupted: finishing the articles under way (press Ctrl-C again to quit)");
+    });
+    if let Err(e) = handled {
+        warn!("Ctrl-C will stop the run outright: {}", e);
+    }
+    let content_options = ContentOptions { interrupt: Some(interrupt), ..content_options };
+    let phase = Instant::now();
+    let stories_with_content = match &mock {
+        Some(http) => fetch_article_content_via(http, &mut stories, &cache, &content_options)?,
+        None => fetch_article_content(&mut stories, &cache, &content_options)?,
+    };
+    times.articles = phase.elapsed();
+    Ok((stories, stories_with_content, filtered))
+}
+
+// Presentation flags for a digest of `day`
+fn render_options(args: &Args, day: NaiveDate, zone: Zone, by_day: bool, previous: Option<Snapshot>) -> RenderOptions {
+    RenderOptions {
+        feed: args.feed,
+        theme: args.theme,
+        by_day,
+        date: day,
+        language: args.lang.clone().unwrap_or_else(|| "en".to_string()),
+        favicons: args.favicons,
+        previous,
+        layout: args.layout,
+        zone,
+        timings: args.show_timings,
+        sidebar_stats: args.sidebar_stats,
+        max_words: args.max_content_words,
+        header_images: args.header_images,
+    }
+}
+
+// `hn_daily render`: another run's outputs, rebuilt from its JSON digest without the network
+fn render_saved(args: &Args, from_json: &Path) -> Result<()> {
+    let (stories, contents) = load_json_digest(from_json, args.thin_words)?;
+    let zone = args.timezone.unwrap_or_default();
+    // A digest named by its date keeps that date unless --date says otherwise
+    let day = args
+        .date
+        .or_else(|| from_json.file_stem()?.to_str()?.parse().ok())
+        .unwrap_or_else(|| zone.today());
+    let options = render_options(args, day, zone, false, None);
+    
+    let formats = args.formats();
+    if formats.contains(&Format::Terminal) {
+        if formats.len() > 1 {
+            return Err(anyhow!("the terminal format writes no files; pass it to --format on its own"));
+        }
+        return show_in_terminal(args, &options, &stories, &contents);
+    }
+    let out_dir = prepare_out_dir(args.out_dir.as_deref())?;
+    let date = day.format("%Y-%m-%d").to_string();
+    let stem = file_stem(&args.filename_template, &date, args.feed.name(), stories.len());
+    let written = write_files(args, &out_dir, &stem, &options, &stories, &contents)?;
+    info!("Files generated in {}", out_dir.display());
+    for (name, description) in &written {
+        info!("- {} - {}", name, description);
+    }
+    Ok(())
+}
+
+// Saves every requested format under `out_dir` (the PDF only when a backend is
+// available), returning each file's name and description
+fn write_files(
+    args: &Args,
+    out_dir: &Path,
+    stem: &str,
+    options: &RenderOptions,
+    stories: &[Item],
+    contents: &[Option<ScrapedContent>],
+) -> Result<Vec<(String, &'static str)>> {
+    let formats = args.formats();
+    let mut written: Vec<(String, &str)> = Vec::new();
+    for &format in Format::value_variants().iter().filter(|f| formats.contains(f)) {
+        // The PDF is printed from a file, see below
+        if format == Format::Pdf {
+            continue;
+        }
+        let name = format.file_name(stem);
+        let path = out_dir.join(&name);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = io::BufWriter::new(fs::File::create(&path)?);
+        write_digest(&mut file, format, args, options, stories, contents)?;
+        file.flush()?;
+        written.push((name, format.description()));
+        if format == Format::Html {
+            // Rebuilt from the directory listing, so it also picks up digests from older runs
+            fs::write(out_dir.join("index.html"), render_archive(args.theme, &digest_files(out_dir, &args.filename_template)))?;
+            written.push(("index.html".to_string(), "Archive of all digests"));
+        }
+    }
+
+    // optional: create PDF when a backend is available
+    let html_path = out_dir.join(Format::Html.file_name(stem));
+    if formats.contains(&Format::Pdf) {
+        // wkhtmltopdf converts from a file, so stage the HTML elsewhere if it isn't being kept
+        let source = if formats.contains(&Format::Html) {
+            html_path.clone()
+        } else {
+            let staged = std::env::temp_dir().join(format!("hn_daily-{}.html", options.date));
+            fs::write(&staged, render_html(options, stories, contents)?)?;
+            staged
         };
+        let pdf_path = out_dir.join(Format::Pdf.file_name(stem));
+        if let Some(parent) = pdf_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let wrote_pdf = write_pdf(&source, &pdf_path, options, stories, contents)?;
+        if source != html_path {
+            fs::remove_file(&source).ok();
+        }
         
-        println!("Fetching: {}", url);
-        
-        match fetch_and_process(&client, url) {
-            Ok(content) => results.push(Some(content)),
-            Err(e) => {
-                println!("Failed to fetch {}: {}", url, e);
-                results.push(None);
-            }
+        if wrote_pdf {
+            written.push((Format::Pdf.file_name(stem), Format::Pdf.description()));
+        } else if args.formats.is_some() {
+            warn!("Skipping PDF: wkhtmltopdf is not installed");
         }
     }
-    
-    Ok(results)
+    Ok(written)
 }
 
-fn fetch_and_process(client: &Client, url: &str) -> Result<ScrapedContent> {
-    let response = match client.get(url).send() {
-        Ok(resp) => {
-            if !resp.status().is_success() {
-                return Err(anyhow!("Failed with status: {}", resp.status()));
+// Renders one format into `sink`, a file in the output directory or standard output
+fn write_digest(
+    sink: &mut dyn Write,
+    format: Format,
+    args: &Args,
+    options: &RenderOptions,
+    stories: &[Item],
+    contents: &[Option<ScrapedContent>],
+) -> Result<()> {
+    match format {
+        Format::Html => sink.write_all(render_html(options, stories, contents)?.as_bytes())?,
+        Format::Text => sink.write_all(render_text(options, stories, contents, args.text_width).as_bytes())?,
+        Format::Md => sink.write_all(render_markdown(options, stories, contents).as_bytes())?,
+        Format::Json => sink.write_all(render_json(stories, contents)?.as_bytes())?,
+        Format::Rss => sink.write_all(render_feed(options, stories, contents).as_bytes())?,
+        Format::Epub => sink.write_all(&render_epub(options, stories, contents)?)?,
+        Format::Mbox => sink.write_all(render_mbox(stories, contents).as_bytes())?,
+        Format::Opml => sink.write_all(render_opml(options, contents).as_bytes())?,
+        Format::JsonFeed => sink.write_all(render_json_feed(options, stories, contents)?.as_bytes())?,
+        Format::Bookmarks => sink.write_all(render_bookmarks(options, stories, contents).as_bytes())?,
+        Format::Pdf => return Err(anyhow!("PDF output is written by write_pdf")),
+        Format::Terminal => return Err(anyhow!("Terminal output is shown by show_in_terminal")),
+    }
+    Ok(())
+}
+
+// Prints the console overview, through a pager when standard output is a terminal.
+// Color follows the terminal too, and NO_COLOR turns it off
+fn show_in_terminal(
+    args: &Args,
+    options: &RenderOptions,
+    stories: &[Item],
+    contents: &[Option<ScrapedContent>],
+) -> Result<()> {
+    let interactive = io::stdout().is_terminal();
+    let color = interactive && std::env::var_os("NO_COLOR").is_none_or(|value| value.is_empty());
+    let overview = render_terminal(options, stories, contents, args.text_width, color);
+    if interactive {
+        let (shell, flag, default_pager) = if cfg!(windows) { ("cmd", "/C", "more") } else { ("sh", "-c", "less") };
+        let pager = std::env::var("PAGER").unwrap_or_else(|_| default_pager.to_string());
+        // Like git: keep colors, and skip paging when everything fits on one screen
+        let spawned = std::process::Command::new(shell)
+            .arg(flag)
+            .arg(&pager)
+            .env("LESS", std::env::var("LESS").unwrap_or_else(|_| "FRX".to_string()))
+            .stdin(std::process::Stdio::piped())
+            .spawn();
+        match spawned {
+            Ok(mut child) => {
+                if let Some(mut stdin) = child.stdin.take() {
+                    // Quitting the pager early closes the pipe, which is not an error
+                    stdin.write_all(overview.as_bytes()).ok();
+                }
+                child.wait()?;
+                return Ok(());
             }
-            resp
+            Err(e) => warn!("Failed to start pager {}: {}", pager, e),
         }
-        Err(e) => return Err(anyhow!("Request failed: {}", e)),
+    }
+    let mut stdout = io::stdout().lock();
+    stdout.write_all(overview.as_bytes())?;
+    stdout.flush()?;
+    Ok(())
+}
+
+// Posts the top stories to --webhook once the digest has been written
+fn announce(args: &Args, options: &RenderOptions, stories: &[Item], contents: &[Option<ScrapedContent>]) {
+    let Some(url) = &args.webhook else {
+        return;
     };
-    
-    let is_paywall = detect_paywall(&response);
-    let domain = extract_domain(url)?;
-    
-    // Get page HTML
-    let html = response.text()?;
-    
-    // Process with Readability
-    let parsed_url = Url::parse(url)?;
-    let mut html_bytes = html.as_bytes();
-    let article = extractor::extract(&mut html_bytes, &parsed_url)?;
-    let content_html = article.content;
-    let content = clean_content(&content_html);
-    
-    Ok(ScrapedContent {
-        title: article.title,
-        content,
-        content_html,
-        is_paywall,
-        domain,
-    })
-}
-
-fn detect_paywall(response: &Response) -> bool {
-    // Simple heuristic - more advanced detection would need specific site handling
-    let content_type = response.headers().get("content-type")
-        .and_then(|v| v.to_str().ok())
-        .unwrap_or("");
-    
-    if !content_type.contains("text/html") {
-        return true; // Not HTML content might be a redirect or paywall
+    let payload = webhook_payload(args.webhook_format, options, stories, contents, args.webhook_top);
+    // The digest itself is done, so a failed announcement is only worth a warning
+    match post_webhook(url, &payload, Duration::from_secs(args.api_timeout), args.proxy.as_deref()) {
+        Ok(()) => info!("Posted the top {} stories to the webhook", args.webhook_top.min(stories.len())),
+        Err(e) => warn!("Failed to post to the webhook: {:#}", e),
+    }
+}
+
+// For incremental runs: the value to pass as --after-id next time, which stays put
+// when nothing new came in. A first run without --after-id starts the sequence
+fn newest_id(args: &Args, stories: &[Item]) -> Option<u64> {
+    let newest = stories
+        .iter()
+        .flat_map(|story| std::iter::once(story).chain(&story.also_covered))
+        .map(|story| story.id)
+        .max();
+    match (newest, args.after_id) {
+        (Some(newest), after_id) => Some(newest.max(after_id.unwrap_or(0))),
+        (None, after_id) => after_id,
+    }
+}
+
+// Standard output is the digest itself with --stdout, so the id goes to stderr
+fn eprint_newest_id(args: &Args, stories: &[Item]) {
+    if let Some(newest) = newest_id(args, stories) {
+        eprintln!("Newest story id: {}", newest);
+    }
+}
+
+// HN ids of the stories --skip-seen runs have shown, kept in the output directory
+fn seen_path(out_dir: &Path) -> PathBuf {
+    out_dir.join("seen.json")
+}
+
+fn load_seen(out_dir: &Path) -> Result<HashSet<u64>> {
+    let path = seen_path(out_dir);
+    match fs::read(&path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("invalid seen stories file {} (`hn_daily forget` resets it)", path.display())),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(HashSet::new()),
+        Err(e) => Err(e).with_context(|| format!("cannot read {}", path.display())),
+    }
+}
+
+// Adds the stories just shown, grouped ones included, so later --skip-seen runs leave
+// them out. Rebuilding an old day from raw HTML shows nothing new
+fn remember_seen(args: &Args, out_dir: &Path, stories: &[Item]) -> Result<()> {
+    if !args.skip_seen || args.from_raw.is_some() {
+        return Ok(());
+    }
+    let mut seen = load_seen(out_dir)?;
+    seen.extend(stories.iter().flat_map(|story| std::iter::once(story).chain(&story.also_covered)).map(|story| story.id));
+    let mut ids: Vec<u64> = seen.into_iter().collect();
+    ids.sort_unstable();
+    let path = seen_path(out_dir);
+    fs::write(&path, serde_json::to_vec(&ids)?).with_context(|| format!("cannot write {}", path.display()))
+}
+
+fn forget_seen(out_dir: &Path) -> Result<()> {
+    let forgotten = load_seen(out_dir).map(|seen| seen.len()).unwrap_or(0);
+    match fs::remove_file(seen_path(out_dir)) {
+        Ok(()) => println!("Forgot {} seen stories", forgotten),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => println!("No seen stories to forget"),
+        Err(e) => return Err(e).context("cannot remove the seen stories file"),
+    }
+    Ok(())
+}
+
+// Article fetching settings shared by the digest and `read`, without snapshots or resuming
+fn content_options(args: &Args, extract_rules: Vec<ExtractRule>) -> ContentOptions {
+    ContentOptions {
+        concurrency: args.concurrency,
+        concurrency_per_host: args.concurrency_per_host,
+        max_retries: args.retries,
+        timeout: Duration::from_secs(args.timeout),
+        wayback: args.wayback,
+        raw_dir: None,
+        progress_file: None,
+        resume: args.resume,
+        extract_rules,
+        extractor: args.extractor,
+        progress_bar: !args.quiet,
+        inline_images: args.inline_images.then_some(args.max_image_kb * 1024),
+        header_images: args.header_images,
+        proxy: args.proxy.clone(),
+        user_agent: user_agent(&args.user_agent),
+        cookies_file: args.cookies_file.clone(),
+        max_page_bytes: args.max_page_mb.saturating_mul(1024 * 1024),
+        retry_empty: args.retry_empty,
+        pool_max_idle_per_host: args.max_idle_per_host.unwrap_or(args.concurrency),
+        thin_words: args.thin_words,
+        interrupt: None,
     }
-    
-    // We could add more sophisticated detection based on cookies, redirects, etc.
-    false
 }
 
-fn clean_content(html: &str) -> String {
-    let document = Html::parse_document(html);
+// `hn_daily read URL`: one article through the digest's extraction and sanitizing
+fn read_article(
+    args: &Args,
+    url: &str,
+    text: bool,
+    output: Option<&Path>,
+    extract_rules: Vec<ExtractRule>,
+) -> Result<()> {
+    let url = normalize_url(url, &args.keep_params).with_context(|| format!("`{}` is not a valid URL", url))?;
+    let mock = args.mock.as_deref().map(MockHttp::load).transpose()?;
+    // A one-off read shouldn't need a writable output directory
+    let cache = Cache { dir: PathBuf::new(), ttl: Duration::ZERO, read: false, write: false };
+    let options = content_options(args, extract_rules);
+    let content = match &mock {
+        Some(http) => fetch_url_content_via(http, &url, &cache, &options),
+        None => fetch_url_content(&url, &cache, &options),
+    }
+    .with_context(|| format!("could not read {}", url))?;
     
-    // Extract text from the HTML document
-    let text = document.root_element()
-        .text()
-        .collect::<Vec<_>>()
-        .join(" ");
+    let html = render_article(args.theme, &url, &content);
+    let rendered = if text { html2text::from_read(html.as_bytes(), 80) } else { html };
+    match output {
+        Some(path) => fs::write(path, rendered).with_context(|| format!("cannot write {}", path.display()))?,
+        None => print!("{}", rendered),
+    }
+    Ok(())
+}
+
+// The story list after cleanup and every filter that doesn't need article content,
+// plus how many stories the domain filters dropped
+fn fetch_stories(
+    args: &Args,
+    limit: usize,
+    mock: Option<&MockHttp>,
+    id_cache: Option<&Path>,
+    seen: &HashSet<u64>,
+) -> Result<(Vec<Item>, usize)> {
+    // Story lists only hold what's current, so a multi-day digest needs a deeper window
+    let window = match args.since_days {
+        Some(days) => limit.saturating_mul(days).min(MAX_LIMIT),
+        None => limit,
+    };
+    info!("Fetching {} {} stories", window, args.feed.name());
+    let mut stories = match mock {
+        Some(http) => fetch_front_page_via(http, args.feed, window)?,
+        None => fetch_front_page(
+            args.feed,
+            window,
+            args.retries,
+            Duration::from_millis(args.api_delay),
+            Duration::from_secs(args.api_timeout),
+            args.proxy.as_deref(),
+            id_cache,
+        )?,
+    };
+    // Clean links once so fetching, caching and every output use the same URL
+    for story in &mut stories {
+        let Some(url) = story.url.take() else { continue };
+        // A link that can't be fetched leaves the story as a discussion-only one
+        let Some(repaired) = repair_url(&url) else {
+            info!("Ignoring unusable link for story {}: {:?}", story.id, url);
+            continue;
+        };
+        if repaired != url {
+            debug!("Repaired link {:?} to {}", url, repaired);
+        }
+        story.url = Some(normalize_url(&repaired, &args.keep_params).unwrap_or(repaired));
+    }
+    let before = stories.len();
+    let mut stories = dedup_stories(stories);
+    if stories.len() < before {
+        info!("Collapsed {} duplicate stories", before - stories.len());
+    }
+    // Filter before fetching content so discarded stories are never scraped
+    if args.after_id.is_some() || args.before_id.is_some() {
+        let before = stories.len();
+        stories.retain(|story| {
+            args.after_id.is_none_or(|id| story.id > id) && args.before_id.is_none_or(|id| story.id < id)
+        });
+        info!("Dropped {} stories outside the id range", before - stories.len());
+    }
+    if !seen.is_empty() {
+        let before = stories.len();
+        stories.retain(|story| !seen.contains(&story.id));
+        info!("Dropped {} stories shown in earlier digests", before - stories.len());
+    }
+    if let Some(min_score) = args.min_score {
+        let before = stories.len();
+        stories.retain(|story| story.score.unwrap_or(0) >= min_score);
+        info!("Dropped {} stories below {} points", before - stories.len(), min_score);
+    }
+    if let Some(min_comments) = args.min_comments {
+        let before = stories.len();
+        stories.retain(|story| story.descendants.unwrap_or(0) >= min_comments);
+        info!("Dropped {} stories with fewer than {} comments", before - stories.len(), min_comments);
+    }
+    let before = stories.len();
+    stories.retain(|story| {
+        let matches = |patterns: &[String]| {
+            story_domain(story).is_some_and(|d| patterns.iter().any(|p| domain_matches(&d, p)))
+        };
+        // Text posts have no domain: they can't be on a blocklist, nor on an allowlist
+        !matches(&args.exclude_domains)
+            && (args.include_domains.is_empty() || matches(&args.include_domains))
+    });
+    let filtered = before - stories.len();
+    if let Some(days) = args.since_days {
+        let zone = args.timezone.unwrap_or_default();
+        stories = select_recent_days(stories, days, limit, zone);
+        let first = stories.iter().filter_map(|story| story_date(story, zone)).min();
+        let last = stories.iter().filter_map(|story| story_date(story, zone)).max();
+        if let (Some(first), Some(last)) = (first, last) {
+            info!("Covering {} to {} ({} stories)", first, last, stories.len());
+        } else {
+            warn!("No stories posted in the last {} days", days);
+        }
+    }
+    if let Some(n) = args.top_n_content {
+        for story in stories.iter_mut().skip(n) {
+            story.title_only = true;
+        }
+    }
+    Ok((stories, filtered))
+}
+
+// The --dry-run report: what a real run would fetch, in the order it would list it
+fn print_plan(args: &Args, stories: Vec<Item>, filtered: usize) {
+    let placeholders = std::iter::repeat_with(|| None).take(stories.len()).collect();
+    let by_day = args.since_days.is_some();
+    let zone = args.timezone.unwrap_or_default();
+    let (stories, placeholders) = sort_stories(stories, placeholders, args.sort, by_day, zone);
+    let (stories, _) = if args.spread_domains {
+        spread_domains(stories, placeholders, by_day, zone)
+    } else {
+        (stories, placeholders)
+    };
+    let articles = stories.iter().filter(|story| !story.title_only && story_domain(story).is_some()).count();
+    println!(
+        "{} {} stories, {} with articles to fetch{}",
+        stories.len(),
+        args.feed.name(),
+        articles,
+        if filtered > 0 { format!(" ({} filtered out by domain)", filtered) } else { String::new() }
+    );
+    for (i, story) in stories.iter().enumerate() {
+        let source = match story_domain(story) {
+            Some(domain) if story.title_only => format!("{}, title only", domain),
+            Some(domain) => domain,
+            None => "text post, nothing to fetch".to_string(),
+        };
+        println!(
+            "{:>3}. {} ({} points, {})",
+            i + 1,
+            story.title.as_deref().unwrap_or("[no title]"),
+            story.score.unwrap_or(0),
+            source
+        );
+    }
+}
+
+// Keeps stories whose article is in `lang` or whose language couldn't be told
+fn filter_language(
+    stories: Vec<Item>,
+    contents: Vec<Option<ScrapedContent>>,
+    lang: &str,
+) -> (Vec<Item>, Vec<Option<ScrapedContent>>) {
+    let before = stories.len();
+    let (stories, contents): (Vec<Item>, Vec<Option<ScrapedContent>>) = stories
+        .into_iter()
+        .zip(contents)
+        .filter(|(_, content)| {
+            content.as_ref().and_then(|c| c.language.as_deref()).is_none_or(|detected| detected == lang)
+        })
+        .unzip();
+    if stories.len() < before {
+        info!("Dropped {} articles not in {}", before - stories.len(), lang);
+    }
+    (stories, contents)
+}
+
+fn filter_paywalled(
+    stories: Vec<Item>,
+    contents: Vec<Option<ScrapedContent>>,
+    mode: PaywallMode,
+) -> (Vec<Item>, Vec<Option<ScrapedContent>>) {
+    let is_paywalled = |content: &Option<ScrapedContent>| content.as_ref().is_some_and(|c| c.is_paywall);
+    let paywalled = contents.iter().filter(|content| is_paywalled(content)).count();
+    if paywalled == 0 || mode == PaywallMode::Show {
+        return (stories, contents);
+    }
+    let (stories, contents) = stories
+        .into_iter()
+        .zip(contents)
+        .filter_map(|(mut story, content)| match mode {
+            _ if !is_paywalled(&content) => Some((story, content)),
+            PaywallMode::TitleOnly => {
+                story.title_only = true;
+                Some((story, None))
+            }
+            _ => None,
+        })
+        .unzip();
+    match mode {
+        PaywallMode::TitleOnly => info!("Listing {} paywalled stories without their articles", paywalled),
+        _ => info!("Dropped {} paywalled stories", paywalled),
+    }
+    (stories, contents)
+}
+
+// Warnings only by default so cron output stays quiet; RUST_LOG still overrides
+fn init_logging(args: &Args) {
+    let level = match (args.quiet, args.verbose) {
+        (true, _) => LevelFilter::Error,
+        (false, 0) => LevelFilter::Warn,
+        (false, 1) => LevelFilter::Info,
+        (false, _) => LevelFilter::Debug,
+    };
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Warn.min(level))
+        .filter_module("hn_daily", level)
+        .format_timestamp(None)
+        .format_target(false)
+        .parse_default_env()
+        .init();
+}
+
+// Never falls back to the working directory: a missing or relative $HOME (say
+// `HOME=.` under cron) would otherwise scatter digests wherever the process started
+fn resolve_out_dir(requested: Option<&Path>) -> Result<PathBuf> {
+    match requested {
+        Some(dir) => Ok(dir.to_path_buf()),
+        None => Ok(dirs::home_dir()
+            .filter(|home| home.is_absolute())
+            .ok_or_else(|| {
+                anyhow!(
+                    "could not determine an absolute home directory; pass --out-dir, set HN_DAILY_DIR \
+                     or set out_dir in the config file"
+                )
+            })?
+            .join("hn_daily")),
+    }
+}
+
+// Resolves the output directory and makes sure we can actually write to it
+fn prepare_out_dir(requested: Option<&Path>) -> Result<PathBuf> {
+    let out_dir = resolve_out_dir(requested)?;
     
-    // Clean up whitespace and ensure reasonable line length
-    let mut result = String::with_capacity(text.len());
-    let mut current_line_length = 0;
+    fs::create_dir_all(&out_dir)
+        .with_context(|| format!("cannot create output directory {}", out_dir.display()))?;
+    let probe = out_dir.join(".hn_daily_write_test");
+    fs::write(&probe, b"")
+        .with_context(|| format!("output directory {} is not writable", out_dir.display()))?;
+    fs::remove_file(&probe).ok();
     
-    for word in text.split_whitespace() {
-        if current_line_length > 0 {
-            result.push(' ');
-            current_line_length += 1;
+    Ok(out_dir)
+}
+
+fn parse_fraction(s: &str) -> Result<f64, String> {
+    match s.parse::<f64>() {
+        Ok(value) if (0.0..=1.0).contains(&value) => Ok(value),
+        _ => Err(format!("`{}` is not a number between 0 and 1", s)),
+    }
+}
+
+fn parse_date(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|_| format!("`{}` is not a YYYY-MM-DD date", s))
+}
+
+fn parse_timezone(s: &str) -> Result<Zone, String> {
+    s.parse().map(Zone::Named).map_err(|_| format!("`{}` is not an IANA time zone like Europe/Berlin", s))
+}
+
+// Fills in a --filename-template. Only the tokens checked by parse_filename_template occur
+fn file_stem(template: &str, date: &str, feed: &str, count: usize) -> String {
+    template.replace("{date}", date).replace("{feed}", feed).replace("{count}", &count.to_string())
+}
+
+// Accepts templates whose names stay inside the output directory: `/` may add
+// subdirectories, but there are no absolute paths, `..`, hidden files or backslashes
+fn parse_filename_template(s: &str) -> Result<String, String> {
+    let mut rest = s;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..].find('}').map(|end| start + end + 1).ok_or("unclosed `{` in template")?;
+        let token = &rest[start..end];
+        if !matches!(token, "{date}" | "{feed}" | "{count}") {
+            return Err(format!("unknown token `{}` (use {{date}}, {{feed}} or {{count}})", token));
         }
-        
-        result.push_str(word);
-        current_line_length += word.len();
-        
-        // Soft break for very long words
-        if word.len() > 30 {
-            result.push(' ');
-            current_line_length = 0;
+        rest = &rest[end..];
+    }
+    if s.contains(['\\', '\0']) {
+        return Err("backslashes are not allowed in file names; use `/` for subdirectories".to_string());
+    }
+    if s.starts_with('/') {
+        return Err("the template must be relative to the output directory".to_string());
+    }
+    // The tokens expand to plain dates, feed names and numbers, so checking the
+    // template itself covers every name it can produce
+    for part in s.split('/') {
+        if part.is_empty() {
+            return Err("the template has an empty file or directory name".to_string());
+        }
+        if part.starts_with('.') {
+            return Err(format!("`{}` would be hidden or leave the output directory", part));
         }
     }
-    
-    result
+    Ok(s.to_string())
 }
 
-fn extract_domain(url: &str) -> Result<String> {
-    let parsed = Url::parse(url)?;
-    parsed.host_str()
-        .map(|h| h.to_string())
-        .ok_or_else(|| anyhow!("No host in URL"))
+fn parse_language(s: &str) -> Result<String, String> {
+    normalize_language(s).ok_or_else(|| format!("`{}` is not an ISO 639 language code", s))
 }
 
-fn render_html(items: &[Item], contents: &[Option<ScrapedContent>]) -> Result<String> {
-    let today = Local::now().format("%B %e, %Y").to_string();
-    
-    // Build index
-    let mut index = String::new();
-    for (i, it) in items.iter().enumerate() {
-        let title = it.title.as_deref().unwrap_or("[no title]");
-        index.push_str(&format!(
-            "<li><a href=\"#article-{}\">{}</a></li>",
-            i, title
-        ));
+fn parse_positive(s: &str) -> Result<usize, String> {
+    let n: usize = s.parse().map_err(|_| format!("`{}` is not a number", s))?;
+    if n == 0 {
+        return Err("value must be at least 1".to_string());
     }
+    Ok(n)
+}
+
+fn parse_positive_secs(s: &str) -> Result<u64, String> {
+    parse_positive(s).map(|n| n as u64)
+}
+
+fn clamp_limit(limit: usize) -> usize {
+    if limit > MAX_LIMIT {
+        warn!("Limit {} exceeds the {} stories HN provides, using {}", limit, MAX_LIMIT, MAX_LIMIT);
+        MAX_LIMIT
+    } else {
+        limit
+    }
+}
+
+// Hands the file to the platform opener; headless machines without one just get a note
+fn open_in_browser(path: &Path) {
+    let (opener, leading_args): (&str, &[&str]) = if cfg!(target_os = "macos") {
+        ("open", &[])
+    } else if cfg!(windows) {
+        ("cmd", &["/C", "start", ""])
+    } else {
+        ("xdg-open", &[])
+    };
     
-    // Build article content
-    let mut articles = String::new();
-    for (i, it) in items.iter().enumerate() {
-        let url = it.url.as_deref().unwrap_or("#");
-        let title = it.title.as_deref().unwrap_or("[no title]");
-        let score = it.score.unwrap_or(0);
-        let by = it.by.as_deref().unwrap_or("unknown");
-        let comments = it.descendants.unwrap_or(0);
-        
-        // Article content section
-        let content_html = match &contents.get(i) {
-            Some(Some(content)) => {
-                // Use full content instead of preview
-                let paywall_warning = if content.is_paywall {
-                    "<div class=\"paywall-warning\">Content may be behind a paywall</div>"
-                } else {
-                    ""
-                };
-                
-                format!(
-                    "<div class=\"content\">\
-                    <div class=\"domain\">{}</div>\
-                    {}\
-                    <div class=\"full-content\">{}</div>\
-                    </div>",
-                    content.domain,
-                    paywall_warning,
-                    content.content_html
-                )
-            },
-            _ => "<div class=\"content\">\
-                  <em>Could not retrieve content</em>\
-                  </div>".to_string(),
-        };
-        
-        articles.push_str(&format!(
-            "<article id=\"article-{}\" class=\"story\">\
-            <h2><a href=\"{}\">{}</a></h2>\
-            <p class=\"meta\">{} points • by {} • {} comments</p>\
-            {}\
-            </article>\
-            <hr>",
-            i,
-            url,
-            title,
-            score,
-            by,
-            comments,
-            content_html
-        ));
-    }
-
-    Ok(format!(
-        "<!DOCTYPE html>\
-<html lang=\"en\">\
-<head>\
-<meta charset=\"utf-8\">\
-<meta name=\"viewport\" content=\"width=device-width, initial-scale=1.0\">\
-<title>Hacker News Daily – {}</title>\
-<style>\
-body{{font-family:Georgia,serif;margin:0;padding:0;display:flex;flex-direction:column;}}\
-h1{{text-align:center;margin:0;padding:20px 0 10px 0;}}\
-.date{{text-align:center;margin:0 0 20px 0;}}\
-.main-container{{display:flex;flex:1;}}\
-.sidebar{{position:sticky;top:0;width:240px;height:100vh;overflow-y:auto;background:#f8f8f8;padding:15px;box-sizing:border-box;border-right:1px solid #ddd;}}\
-.sidebar h2{{text-align:center;margin-top:0;font-size:1.2em;}}\
-.story-index{{padding-left:20px;margin:0;}}\
-.story-index li{{margin-bottom:0.8em;font-size:0.85em;}}\
-.articles{{flex:1;padding:20px;overflow-y:auto;box-sizing:border-box;}}\
-.article-container{{max-width:700px;margin:0 auto;}}\
-.story{{margin-bottom:1.5em;}}\
-.story h2{{font-size:1.2em;margin:1em 0 .1em 0;}}\
-.meta{{font-size:.8em;color:#555;margin:0 0 .5em 0;}}\
-.content{{font-size:0.85em;margin-top:0.5em;}}\
-.domain{{color:#888;font-size:0.9em;margin-bottom:0.3em;}}\
-.full-content{{line-height:1.5;margin-top:1em;overflow-wrap:break-word;word-wrap:break-word;}}\
-.full-content p{{margin:0.7em 0;}}\
-/* Improve content display */\
-.full-content img{{max-width:100%;height:auto;}}\
-.full-content pre, .full-content code{{max-width:100%;overflow-x:auto;white-space:pre-wrap;background:#f5f5f5;padding:2px 4px;border-radius:3px;}}\
-.full-content table{{max-width:100%;overflow-x:auto;border-collapse:collapse;}}\
-.full-content th, .full-content td{{border:1px solid #ddd;padding:4px 8px;}}\
-.paywall-warning{{color:#aa3300;font-style:italic;margin-bottom:0.3em;}}\
-.back-to-top{{display:none;}}\
-hr{{border:0;border-top:1px solid #ddd;margin:2em 0;}}\
-a{{color:#000;text-decoration:none;}}\
-a:hover{{text-decoration:underline;}}\
-a.active{{font-weight:bold;color:#ff6600;background:#fff3e0;padding:2px 5px;border-radius:3px;margin-left:-5px;}}\
-@media print{{.sidebar{{display:none;}} .articles{{margin:0;max-width:none;}} a{{color:#000}}}}\
-@media (max-width: 800px) {{.main-container{{flex-direction:column;}} .sidebar{{position:static;width:100%;height:auto;}} .articles{{padding:15px;}}}}\
-</style>\
-<script>\
-// Handle direct click navigation and sync with scroll position
-document.addEventListener(\"DOMContentLoaded\", function() {{\
-  const articles = document.querySelectorAll(\".story\");\
-  const links = document.querySelectorAll(\".story-index a\");\
-  
-  // Handle link clicks
-  links.forEach(link => {{\
-    link.addEventListener(\"click\", function(e) {{\
-      // Remove active class from all links
-      links.forEach(l => l.classList.remove(\"active\"));\
-      // Add active class to clicked link
-      this.classList.add(\"active\");\
-    }});\
-  }});\
-  
-  // Use a better IntersectionObserver for scroll highlighting
-  const observerOptions = {{\
-    root: null, // viewport
-    rootMargin: \"-100px 0px -300px 0px\", // top, right, bottom, left margins
-    threshold: 0.2 // 20% of the element should be visible
-  }};\
-  
-  let currentActiveLink = null;\
-  
-  const observer = new IntersectionObserver((entries) => {{\
-    entries.forEach(entry => {{\
-      // When an article comes into view
-      if (entry.isIntersecting && entry.intersectionRatio >= 0.2) {{\
-        const id = entry.target.id;\
-        const targetLink = document.querySelector(\".story-index a[href='#\" + id + \"']\");\
-        
-        if (targetLink && targetLink !== currentActiveLink) {{\
-          // Remove active class from all links
-          links.forEach(link => link.classList.remove(\"active\"));\
-          
-          // Add active class to corresponding link
-          targetLink.classList.add(\"active\");\
-          currentActiveLink = targetLink;\
-        }}\
-      }}\
-    }});\
-  }}, observerOptions);\
-  
-  // Observe all articles
-  articles.forEach(article => {{\
-    observer.observe(article);\
-  }});\
-  
-  // Set the first item as active by default if we're at the top of the page
-  if (window.scrollY < 100 && links.length > 0) {{\
-    links[0].classList.add(\"active\");\
-    currentActiveLink = links[0];\
-  }}\
-}});\
-</script>\
-</head>\
-<body>\
-<h1>Hacker News Daily</h1>\
-<p class=\"date\">{}</p>\
-\
-<div class=\"main-container\">\
-  <div class=\"sidebar\">\
-    <h2>Article Index</h2>\
-    <ol class=\"story-index\">\
-      {}\
-    </ol>\
-  </div>\
-\
-  <div class=\"articles\">\
-    <div class=\"article-container\">\
-      {}\
-    </div>\
-  </div>\
-</div>\
-\
-</body></html>",
-        today,
-        today,
-        index,
-        articles
-    ))
-}
\ No newline at end of file
+    // Printed whatever the log level, since a headless opener can fail without a word
+    eprintln!("Opening {}", path.display());
+    if which::which(opener).is_err() {
+        warn!("No {} available; open {} manually", opener, path.display());
+        return;
+    }
+    match std::process::Command::new(opener).args(leading_args).arg(path).status() {
+        Ok(status) if !status.success() => warn!("{} could not open {} ({})", opener, path.display(), status),
+        Ok(_) => {}
+        Err(e) => warn!("Failed to open {} with {}: {}", path.display(), opener, e),
+    }
+}
@@ -0,0 +1,106 @@
+//! `hn_daily serve`: a small local web server for reading generated digests.
+
+use anyhow::{anyhow, Result};
+use hn_daily::{digest_files, render_archive, Theme};
+use log::{debug, warn};
+use std::fs;
+use std::io::Cursor;
+use std::path::{Path, PathBuf};
+use tiny_http::{Header, Method, Request, Response, Server};
+
+type Reply = Response<Cursor<Vec<u8>>>;
+
+/// Serves `out_dir` on `bind:port` until the process is stopped. `/` is the
+/// latest digest and `/archive` lists every day on disk, both found by the
+/// file name `template`.
+pub fn run(out_dir: &Path, template: &str, bind: &str, port: u16, theme: Theme) -> Result<()> {
+    let server = Server::http((bind, port))
+        .map_err(|e| anyhow!("cannot listen on {}:{}: {}", bind, port, e))?;
+    // Resolved once so every request is checked against the same real path
+    let root = out_dir.canonicalize()?;
+    println!("Serving digests from {} at http://{}:{}/", root.display(), bind, port);
+
+    for request in server.incoming_requests() {
+        let response = respond(&root, template, &request, theme);
+        if let Err(e) = request.respond(response) {
+            debug!("Failed to send response: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn respond(root: &Path, template: &str, request: &Request, theme: Theme) -> Reply {
+    if !matches!(request.method(), Method::Get | Method::Head) {
+        return error_page(405, "Method not allowed");
+    }
+    let path = request.url().split(['?', '#']).next().unwrap_or("/");
+    debug!("GET {}", path);
+
+    match path {
+        "/" => match digest_files(root, template).first() {
+            Some(latest) => serve_file(root, &latest.path),
+            None => error_page(404, "No digests yet. Run hn_daily to generate one."),
+        },
+        // Links on the archive page are relative, so it must sit at the top level
+        "/archive" => archive_page(root, template, theme),
+        _ => serve_file(root, path.trim_start_matches('/')),
+    }
+}
+
+fn archive_page(root: &Path, template: &str, theme: Theme) -> Reply {
+    let html = render_archive(theme, &digest_files(root, template));
+    with_type(Response::from_data(html.into_bytes()), "text/html; charset=utf-8")
+}
+
+// Only plain files inside the output directory are served: nothing from the
+// cache or raw snapshots, no dotfiles, and nothing reached through `..` or a
+// symlink pointing elsewhere. Other subdirectories hold digests filed there by
+// a --filename-template
+fn serve_file(root: &Path, name: &str) -> Reply {
+    let Some(path) = resolve(root, name) else {
+        return error_page(404, "Not found");
+    };
+    match fs::read(&path) {
+        Ok(data) => with_type(Response::from_data(data), content_type(&path)),
+        Err(e) => {
+            warn!("Failed to read {}: {}", path.display(), e);
+            error_page(500, "Could not read file")
+        }
+    }
+}
+
+fn resolve(root: &Path, name: &str) -> Option<PathBuf> {
+    if name.contains(['\\', '%', ':']) || name.split('/').any(|part| part.is_empty() || part.starts_with('.')) {
+        return None;
+    }
+    let path = root.join(name).canonicalize().ok()?;
+    let inside = path.strip_prefix(root).ok()?;
+    let private = matches!(inside.components().next(), Some(c) if c.as_os_str() == "cache" || c.as_os_str() == "raw");
+    (!private && path.is_file()).then_some(path)
+}
+
+fn content_type(path: &Path) -> &'static str {
+    if path.file_name().is_some_and(|name| name == "feed.json") {
+        return "application/feed+json";
+    }
+    match path.extension().and_then(|ext| ext.to_str()) {
+        Some("html") => "text/html; charset=utf-8",
+        Some("txt") | Some("md") => "text/plain; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/rss+xml",
+        Some("epub") => "application/epub+zip",
+        Some("pdf") => "application/pdf",
+        Some("mbox") => "application/mbox",
+        Some("opml") => "text/x-opml",
+        _ => "application/octet-stream",
+    }
+}
+
+fn error_page(status: u16, message: &str) -> Reply {
+    with_type(Response::from_string(message).with_status_code(status), "text/plain; charset=utf-8")
+}
+
+fn with_type(response: Reply, content_type: &str) -> Reply {
+    let header = Header::from_bytes("Content-Type", content_type).expect("valid header");
+    response.with_header(header)
+}
@@ -0,0 +1,115 @@
+//! `hn_daily stats`: which sites dominate the saved digests.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Local, NaiveDate};
+use hn_daily::{domain_stats, load_json_digest, DomainStats, Item, ScrapedContent, DEFAULT_THIN_WORDS};
+use log::debug;
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Reads every JSON digest under `out_dir`, prints the `top` most frequent sites
+/// and writes all of them to `csv` if given. Nothing in `out_dir` is changed.
+pub fn run(out_dir: &Path, top: usize, csv: Option<&Path>) -> Result<()> {
+    let mut files = Vec::new();
+    if out_dir.is_dir() {
+        find_json(out_dir, &mut files)?;
+    }
+    // Oldest first, so a story seen twice ends up with the scores of its latest digest
+    files.sort_by_cached_key(|path| digest_time(out_dir, path));
+
+    let mut stories: HashMap<u64, (Item, Option<ScrapedContent>)> = HashMap::new();
+    let mut digests = 0;
+    for path in &files {
+        // Feeds, the seen stories and other JSON files aren't digests
+        let digest = load_json_digest(path, DEFAULT_THIN_WORDS);
+        let Ok((items, contents)) = digest.map_err(|e| debug!("Skipping {}: {:#}", path.display(), e)) else {
+            continue;
+        };
+        digests += 1;
+        for (item, content) in items.into_iter().zip(contents) {
+            stories.insert(item.id, (item, content));
+        }
+    }
+    if digests == 0 {
+        println!("No JSON digests in {}. Run hn_daily with the json format first.", out_dir.display());
+        return Ok(());
+    }
+
+    let (items, contents): (Vec<Item>, Vec<Option<ScrapedContent>>) = stories.into_values().unzip();
+    let stats = domain_stats(&items, &contents);
+    println!("{} stories from {} digests, linking to {} sites", items.len(), digests, stats.len());
+    print_table(&stats[..top.min(stats.len())]);
+
+    if let Some(path) = csv {
+        fs::write(path, to_csv(&stats)).with_context(|| format!("cannot write {}", path.display()))?;
+        println!("Wrote {} sites to {}", stats.len(), path.display());
+    }
+    Ok(())
+}
+
+// JSON files anywhere a --filename-template could have put a digest; the article
+// cache, raw snapshots and hidden files are skipped
+fn find_json(dir: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    let entries = fs::read_dir(dir).with_context(|| format!("cannot read {}", dir.display()))?;
+    for entry in entries {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') {
+            continue;
+        }
+        let path = entry.path();
+        if entry.file_type()?.is_dir() {
+            if name != "cache" && name != "raw" {
+                find_json(&path, files)?;
+            }
+        } else if path.extension().is_some_and(|ext| ext == "json") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+// When a digest was for: the date in its path (from a --filename-template with
+// {date}), else the day it was last written, with the write time breaking ties
+fn digest_time(out_dir: &Path, path: &Path) -> (NaiveDate, SystemTime) {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).unwrap_or(SystemTime::UNIX_EPOCH);
+    let relative = path.strip_prefix(out_dir).unwrap_or(path).to_string_lossy();
+    let named = relative
+        .char_indices()
+        .filter_map(|(start, _)| relative.get(start..start + 10))
+        .find_map(|text| NaiveDate::parse_from_str(text, "%Y-%m-%d").ok());
+    let day = named.unwrap_or_else(|| DateTime::<Local>::from(modified).date_naive());
+    (day, modified)
+}
+
+fn print_table(stats: &[DomainStats]) {
+    let width = stats.iter().map(|site| site.domain.chars().count()).max().unwrap_or(0).max("Site".len());
+    println!();
+    println!("{:>4}  {:<width$}  {:>7}  {:>9}", "#", "Site", "Stories", "Avg score");
+    for (rank, site) in stats.iter().enumerate() {
+        println!(
+            "{:>4}  {:<width$}  {:>7}  {:>9.1}",
+            rank + 1,
+            site.domain,
+            site.stories,
+            site.average_score
+        );
+    }
+}
+
+fn to_csv(stats: &[DomainStats]) -> String {
+    let mut csv = String::from("domain,stories,average_score\n");
+    for site in stats {
+        // Host names never need quoting, but a quote keeps an odd one from shifting columns
+        let domain = if site.domain.contains([',', '"']) {
+            format!("\"{}\"", site.domain.replace('"', "\"\""))
+        } else {
+            site.domain.clone()
+        };
+        csv.push_str(&format!("{},{},{:.1}\n", domain, site.stories, site.average_score));
+    }
+    csv
+}